@@ -1,8 +1,11 @@
+use actix::{Actor, StreamHandler};
 use actix_web::{
-    body::BoxBody, error, get, http::header::ContentType, post, web, App, Error, HttpResponse, HttpServer, Responder
+    body::BoxBody, error, get, http::header::ContentType, post, web, App, Error, HttpResponse, HttpServer, Responder,
+    ResponseError,
 };
+use actix_web_actors::ws;
 use serde::{Deserialize, Serialize};
-use std::{sync::Mutex, time::Duration};
+use std::{fmt, sync::Mutex, time::Duration};
 
 #[get("/")]
 async fn hello() -> impl Responder {
@@ -55,6 +58,46 @@ async fn app_visits(data: web::Data<AppStateWithCounter>) -> impl Responder {
     format!("Visits so far: {counter}")
 }
 
+// A WebSocket connection is modelled as an actor. Since `StreamHandler::handle`
+// can't be extended with extractors the way a regular handler can, any shared
+// state the actor needs has to be captured at construction time instead.
+struct Ws {
+    data: web::Data<AppStateWithCounter>,
+}
+
+impl Actor for Ws {
+    type Context = ws::WebsocketContext<Self>;
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for Ws {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Text(text)) if text == "incr" => {
+                let mut counter = self.data.counter.lock().unwrap();
+                *counter += 1;
+                ctx.text(counter.to_string());
+            }
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            _ => (),
+        }
+    }
+}
+
+// Upgrades the connection to a WebSocket and hands off to the `Ws` actor,
+// passing in the shared counter state so `handle` can reach it.
+#[get("/ws/counter")]
+async fn ws_counter(
+    req: actix_web::HttpRequest,
+    stream: web::Payload,
+    data: web::Data<AppStateWithCounter>,
+) -> Result<HttpResponse, Error> {
+    ws::start(Ws { data }, &req, stream)
+}
+
 #[derive(Deserialize)]
 struct AppPathInfo {
     user_id: u32,
@@ -141,20 +184,248 @@ async fn app_profile(username: web::Path<String>) -> impl Responder {
     AppResponse { username: username.to_string() }
 }
 
-use futures::{future, stream};
+// A quote fetched from the upstream pricing API, shaped to match its JSON body.
+#[derive(Deserialize)]
+struct UpstreamQuote {
+    price: f64,
+}
+
+// The reshaped payload this server actually returns, built from `UpstreamQuote`.
+#[derive(Serialize)]
+struct ProxyResponse {
+    symbol: String,
+    price: f64,
+}
+
+// Errors from the outbound request or the upstream body are collapsed into a
+// single type so they can be mapped to an HTTP response via `ResponseError`.
+#[derive(Debug)]
+enum ProxyError {
+    Request(awc::error::SendRequestError),
+    Payload(awc::error::JsonPayloadError),
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProxyError::Request(err) => write!(f, "upstream request failed: {err}"),
+            ProxyError::Payload(err) => write!(f, "upstream response was malformed: {err}"),
+        }
+    }
+}
+
+impl ResponseError for ProxyError {
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::BadGateway().body(self.to_string())
+    }
+}
+
+// Demonstrates composing a handler with an outbound async client instead of
+// blocking I/O. `awc::Client` is stored in `web::Data` so the underlying
+// connections are pooled across requests rather than rebuilt each time.
+#[get("/proxy/{symbol}")]
+async fn app_proxy(
+    symbol: web::Path<String>,
+    client: web::Data<awc::Client>,
+) -> Result<HttpResponse, ProxyError> {
+    let symbol = symbol.into_inner();
+    let upstream_url = format!("https://example.com/api/quotes/{symbol}");
+
+    let mut res = client
+        .get(upstream_url)
+        .send()
+        .await
+        .map_err(ProxyError::Request)?;
+
+    let quote = res
+        .json::<UpstreamQuote>()
+        .await
+        .map_err(ProxyError::Payload)?;
+
+    Ok(HttpResponse::Ok().json(ProxyResponse {
+        symbol,
+        price: quote.price,
+    }))
+}
+
+use tokio_stream::{wrappers::IntervalStream, StreamExt};
 
-// The response body can also be generated asynchronously. In this case,
-// the body must implement `Stream<Item = Result<Bytes, Error>>` and the
-// response is called with `.streaming()`.
-#[get("/stream")]
-async fn app_stream() -> impl Responder {
-    let body = stream::once(future::ok::<_, Error>(web::Bytes::from_static(b"streamed body")));
+// The response body can also be generated asynchronously. In this case, the
+// body must implement `Stream<Item = Result<Bytes, Error>>` and the response
+// is sent with `.streaming()`. Here a `tokio::time::interval` drives a
+// long-lived Server-Sent Events stream: the connection is held open with
+// `content_type("text/event-stream")` and each tick is formatted as an SSE
+// frame carrying the current value of the shared counter. The stream ends,
+// and backpressure is respected, for free once the client disconnects, since
+// `IntervalStream` is simply dropped along with the response body.
+#[get("/events")]
+async fn app_events(data: web::Data<AppStateWithCounter>) -> impl Responder {
+    let ticks = IntervalStream::new(tokio::time::interval(Duration::from_secs(1)));
+
+    let body = ticks.map(move |_| {
+        let counter = *data.counter.lock().unwrap();
+        Ok::<_, Error>(web::Bytes::from(format!(
+            "data: {{\"counter\":{counter}}}\n\n"
+        )))
+    });
 
     HttpResponse::Ok()
-        .content_type(ContentType::json())
+        .content_type("text/event-stream")
         .streaming(body)
 }
 
+// Middleware hooks into the request/response cycle around the inner service.
+// Implemented with the `Transform` + `Service` traits rather than a closure so
+// state (e.g. the start time) can be carried through to the response side.
+mod middleware {
+    use actix_web::{
+        body::EitherBody,
+        dev::{Service, ServiceRequest, ServiceResponse, Transform},
+        http::header::{HeaderName, HeaderValue},
+        Error, HttpResponse,
+    };
+    use futures_util::future::LocalBoxFuture;
+    use std::{
+        future::{ready, Ready},
+        time::Instant,
+    };
+
+    // Records how long the inner service took to respond and reports it back
+    // to the client via the `X-Response-Time-ms` header.
+    pub struct Timing;
+
+    impl<S, B> Transform<S, ServiceRequest> for Timing
+    where
+        S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+        S::Future: 'static,
+        B: 'static,
+    {
+        type Response = ServiceResponse<B>;
+        type Error = Error;
+        type Transform = TimingMiddleware<S>;
+        type InitError = ();
+        type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+        fn new_transform(&self, service: S) -> Self::Future {
+            ready(Ok(TimingMiddleware { service }))
+        }
+    }
+
+    pub struct TimingMiddleware<S> {
+        service: S,
+    }
+
+    impl<S, B> Service<ServiceRequest> for TimingMiddleware<S>
+    where
+        S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+        S::Future: 'static,
+        B: 'static,
+    {
+        type Response = ServiceResponse<B>;
+        type Error = Error;
+        type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        actix_web::dev::forward_ready!(service);
+
+        fn call(&self, req: ServiceRequest) -> Self::Future {
+            let start = Instant::now();
+            let fut = self.service.call(req);
+
+            Box::pin(async move {
+                let mut res = fut.await?;
+                let elapsed_ms = start.elapsed().as_millis().to_string();
+                res.headers_mut().insert(
+                    HeaderName::from_static("x-response-time-ms"),
+                    HeaderValue::from_str(&elapsed_ms).unwrap(),
+                );
+                Ok(res)
+            })
+        }
+    }
+
+    // Rejects requests that don't carry a recognised `Authorization` header
+    // before the wrapped handler runs at all.
+    pub struct ApiKey;
+
+    impl<S, B> Transform<S, ServiceRequest> for ApiKey
+    where
+        S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+        S::Future: 'static,
+        B: 'static,
+    {
+        type Response = ServiceResponse<EitherBody<B>>;
+        type Error = Error;
+        type Transform = ApiKeyMiddleware<S>;
+        type InitError = ();
+        type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+        fn new_transform(&self, service: S) -> Self::Future {
+            ready(Ok(ApiKeyMiddleware { service }))
+        }
+    }
+
+    pub struct ApiKeyMiddleware<S> {
+        service: S,
+    }
+
+    impl<S, B> Service<ServiceRequest> for ApiKeyMiddleware<S>
+    where
+        S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+        S::Future: 'static,
+        B: 'static,
+    {
+        type Response = ServiceResponse<EitherBody<B>>;
+        type Error = Error;
+        type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        actix_web::dev::forward_ready!(service);
+
+        fn call(&self, req: ServiceRequest) -> Self::Future {
+            let is_valid = req
+                .headers()
+                .get("Authorization")
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value == "Bearer secret-api-key")
+                .unwrap_or(false);
+
+            if !is_valid {
+                let (req, _) = req.into_parts();
+                let res = HttpResponse::Unauthorized().finish().map_into_right_body();
+                return Box::pin(async move { Ok(ServiceResponse::new(req, res)) });
+            }
+
+            let fut = self.service.call(req);
+            Box::pin(async move { fut.await.map(ServiceResponse::map_into_left_body) })
+        }
+    }
+}
+
+// Loads a cert chain and private key from the paths named by `TLS_CERT` and
+// `TLS_KEY`, and builds a `rustls::ServerConfig` with ALPN advertising both
+// `h2` and `http/1.1` so HTTP/2 can be negotiated over TLS.
+fn load_rustls_config(cert_path: &str, key_path: &str) -> std::io::Result<rustls::ServerConfig> {
+    let cert_file = &mut std::io::BufReader::new(std::fs::File::open(cert_path)?);
+    let key_file = &mut std::io::BufReader::new(std::fs::File::open(key_path)?);
+
+    let cert_chain = rustls_pemfile::certs(cert_file).collect::<Result<Vec<_>, _>>()?;
+    // `private_key` auto-detects PKCS#1, PKCS#8 and SEC1 (EC) encodings, unlike
+    // `pkcs8_private_keys`, which only recognises PKCS#8.
+    let key_der = rustls_pemfile::private_key(key_file)?.ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("no private key found in {key_path}"),
+        )
+    })?;
+
+    let mut config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, key_der)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // `HttpServer` accepts an application factory instead of an application
@@ -164,10 +435,11 @@ async fn main() -> std::io::Result<()> {
     let counter = web::Data::new(AppStateWithCounter {
         counter: Mutex::new(0),
     });
+    let client = web::Data::new(awc::Client::default());
 
     // Application state doesn't need to be `Send` or `Sync` but application
     // factories must be `Send + Sync`.
-    HttpServer::new(move || {
+    let mut server = HttpServer::new(move || {
         App::new()
             .service(hello)
             .service(echo)
@@ -178,13 +450,22 @@ async fn main() -> std::io::Result<()> {
             // resources and routes attached to it.
             .service(
                 web::scope("/app")
+                    // Middleware runs in reverse registration order on the
+                    // request path (so `ApiKey` sees the request before
+                    // `Timing` does) and in registration order on the
+                    // response path.
+                    .wrap(middleware::Timing)
+                    .wrap(middleware::ApiKey)
                     // Register state to the scope.
                     .app_data(web::Data::new(AppState {
                         app_name: String::from("Actix Web"),
                     }))
                     .app_data(counter.clone()) // Internally `Arc`.
+                    .app_data(client.clone())
                     .service(app_index)
                     .service(app_visits)
+                    .service(ws_counter)
+                    .service(app_proxy)
                     .service(app_path)
                     .service(app_query)
                     .app_data(
@@ -199,16 +480,27 @@ async fn main() -> std::io::Result<()> {
                             }),
                     )
                     .service(app_submit)
-                    .service(app_profile),
+                    .service(app_profile)
+                    .service(app_events),
             )
     })
-    .bind(("127.0.0.1", 8080))?
     // `HttpServer` starts a number of HTTP _workers_, by default equal in
     // number to the number of physical CPUs in the system. This can be
     // overridden with the `HttpServer::workers()` method.
     .workers(8)
-    .run()
-    // The server must be `await`ed or `spawn`ed to start processing requests
-    // and will run until it receives a shutdown signal `ctrl-c`.
-    .await
+    // Always bind plaintext HTTP/1.1 as a fallback.
+    .bind(("127.0.0.1", 8080))?;
+
+    // Additionally bind an HTTPS listener with HTTP/2 support when cert/key
+    // paths are provided via the environment.
+    if let (Ok(cert_path), Ok(key_path)) = (std::env::var("TLS_CERT"), std::env::var("TLS_KEY")) {
+        let rustls_config = load_rustls_config(&cert_path, &key_path)?;
+        server = server.bind_rustls_0_23(("127.0.0.1", 8443), rustls_config)?;
+    }
+
+    server
+        .run()
+        // The server must be `await`ed or `spawn`ed to start processing requests
+        // and will run until it receives a shutdown signal `ctrl-c`.
+        .await
 }